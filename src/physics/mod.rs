@@ -1,10 +1,27 @@
-use std::cmp::Eq;
-use std::fmt;
+#[cfg(feature = "std")]
+use std::boxed::Box;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+
+use core::cmp::Eq;
+use core::fmt;
 
 use crate::geometry::{Point, Vector};
 use crate::physics::field::{BHField, Field};
-use crate::util::write::DataWriter;
-
+#[cfg(feature = "std")]
+use crate::util::write::{DataWriter, OutputSink, SyncSink};
+
+// TODO(no_std): only Mass, Body, Environment, and the step() loop in this
+// file have been converted/verified for no_std + alloc so far. force,
+// barneshut, and field (e.g. any HashMap-based tree in barneshut) are out
+// of scope here and still need their own no_std audit as a follow-up;
+// `cargo build --no-default-features` will not succeed until that lands.
 pub mod force;
 pub mod barneshut;
 pub mod field;
@@ -48,45 +65,94 @@ impl Mass {
 // Environment ///////////////////////////////////////////////////////////////
 //
 // An environment represents a space in which bodies interact with fields.
+// With the "std" feature (on by default) it owns a boxed OutputSink and
+// hands it each frame as it steps; without it (e.g. on an embedded target
+// with no filesystem) it has no sink and hands the frame's points back to
+// the caller instead.
 
+#[cfg(feature = "std")]
 pub struct Environment {
     pub bodies: Vec<Body>,
     pub fields: Vec<Box<dyn Field>>,
-    writer: DataWriter,
+    writer: Box<dyn OutputSink>,
 }
 
+#[cfg(feature = "std")]
 impl Default for Environment {
     fn default() -> Self {
         let field = BHField::new();
         Environment {
             bodies: vec![],
             fields: vec![Box::from(field)],
-            writer: DataWriter::new("data"),
+            writer: Box::new(SyncSink::new(DataWriter::in_directory("data"))),
         }
     }
 }
 
+#[cfg(feature = "std")]
 impl Environment {
-    pub fn new(fields: Vec<Box<dyn Field>>, writer: DataWriter) -> Environment {
-        Environment { fields, writer, ..Self::default() }
+    pub fn new(fields: Vec<Box<dyn Field>>, writer: Box<dyn OutputSink>) -> Environment {
+        Environment { bodies: vec![], fields, writer }
     }
 
     pub fn update(&mut self) {
-        for field in self.fields.iter() {
-            let forces = field.forces(&self.bodies[..]);
+        let points = step(&self.fields, &mut self.bodies[..]);
+        self.writer.send(points);
+    }
+
+    /// Drains any frames still in flight on the sink (e.g. an AsyncSink's
+    /// background thread) before the run ends.
+    pub fn flush(&mut self) {
+        self.writer.flush();
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub struct Environment {
+    pub bodies: Vec<Body>,
+    pub fields: Vec<Box<dyn Field>>,
+}
 
-            for (body, force) in self.bodies.iter_mut().zip(forces.iter()) {
-                body.apply_force(force);
-            }
+#[cfg(not(feature = "std"))]
+impl Default for Environment {
+    // `BHField` itself is unaudited for no_std/alloc (see the TODO on the
+    // `force`/`barneshut`/`field` modules above), so this can't default to
+    // it the way the "std" Environment does; callers must supply fields.
+    fn default() -> Self {
+        Environment {
+            bodies: vec![],
+            fields: vec![],
         }
+    }
+}
 
-        for body in self.bodies.iter_mut() {
-            body.apply_velocity();
+#[cfg(not(feature = "std"))]
+impl Environment {
+    pub fn new(fields: Vec<Box<dyn Field>>) -> Environment {
+        Environment { bodies: vec![], fields }
+    }
+
+    /// Steps the simulation and returns the frame's points directly, since
+    /// there is no filesystem to persist them to.
+    pub fn update(&mut self) -> Vec<Point> {
+        step(&self.fields, &mut self.bodies[..])
+    }
+}
+
+fn step(fields: &[Box<dyn Field>], bodies: &mut [Body]) -> Vec<Point> {
+    for field in fields.iter() {
+        let forces = field.forces(&bodies[..]);
+
+        for (body, force) in bodies.iter_mut().zip(forces.iter()) {
+            body.apply_force(force);
         }
+    }
 
-        let points = self.bodies.iter().map(|b| b.position.clone()).collect();
-        self.writer.write(points);
+    for body in bodies.iter_mut() {
+        body.apply_velocity();
     }
+
+    bodies.iter().map(|b| b.position.clone()).collect()
 }
 
 // Body //////////////////////////////////////////////////////////////////////
@@ -202,3 +268,31 @@ mod tests {
         assert_eq!(Point::new(-1.0, 7.0), sut.position);
     }
 }
+
+// no_std smoke test /////////////////////////////////////////////////////////
+//
+// Steps an Environment with no fields and the "std" feature disabled, to
+// confirm Mass/Body/Environment's own stepping logic never touches the
+// filesystem. This does not exercise force/barneshut/field, so
+// `cargo test --no-default-features` only becomes a real end-to-end no_std
+// check once those modules pass their own audit (see the TODO above).
+
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_tests {
+    use crate::geometry::{Point, Vector};
+
+    use super::*;
+
+    #[test]
+    fn environment_steps_without_a_filesystem() {
+        // given
+        let mut sut = Environment::new(vec![]);
+        sut.bodies.push(Body::new(1.0, Point::zero(), Vector::zero()));
+
+        // when
+        let points = sut.update();
+
+        // then
+        assert_eq!(points, vec![Point::zero()]);
+    }
+}
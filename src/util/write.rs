@@ -1,47 +1,193 @@
+#![cfg(feature = "std")]
+
 use std::fs;
+use std::io;
 use std::io::Write;
 use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
+use std::thread::JoinHandle;
 
 use crate::geometry::Point;
 
-// DataWriter ////////////////////////////////////////////////////////////////
+// FrameSerializer ///////////////////////////////////////////////////////////
 //
-// A utility object to simplify persistence of point data. Each call to
-// write will generate a new file in the specified directory. Files are
-// sequentially numbered.
+// A FrameSerializer owns the on-the-wire layout of a single frame of point
+// data. Implementors only need to know how to turn a frame number and its
+// points into bytes; DataWriter takes care of where those bytes go.
 
-pub struct DataWriter {
-    directory: String,
-    counter: u32,
+pub trait FrameSerializer {
+    fn write_frame(&mut self, w: &mut dyn Write, frame: usize, points: &[Point]) -> io::Result<()>;
 }
 
-impl DataWriter {
-    /// Creates a new directory in the current working path.
-    pub fn new(directory: &str) -> DataWriter {
-        if !Path::new(directory).exists() {
-            fs::create_dir(directory)
-                .expect("Couldn't create dir.");
+// CsvSerializer /////////////////////////////////////////////////////////////
+//
+// Writes each point on its own line as "{x},{y}\n", preceded by a "#,{frame}"
+// comment line marking where the frame starts. This is the layout DataWriter
+// used to hardcode, plus the frame marker needed now that every frame shares
+// one file instead of getting its own.
+
+#[derive(Default)]
+pub struct CsvSerializer;
+
+impl FrameSerializer for CsvSerializer {
+    fn write_frame(&mut self, w: &mut dyn Write, frame: usize, points: &[Point]) -> io::Result<()> {
+        writeln!(w, "#,{}", frame)?;
+        for point in points {
+            writeln!(w, "{},{}", point.x, point.y)?;
         }
-        DataWriter {
-            directory: directory.to_owned(),
-            counter: 0
+        Ok(())
+    }
+}
+
+// BinarySerializer //////////////////////////////////////////////////////////
+//
+// A compact layout: a little-endian u32 frame header followed by a
+// little-endian u32 point count, then each point as a pair of little-endian
+// f32s.
+
+#[derive(Default)]
+pub struct BinarySerializer;
+
+impl FrameSerializer for BinarySerializer {
+    fn write_frame(&mut self, w: &mut dyn Write, frame: usize, points: &[Point]) -> io::Result<()> {
+        w.write_all(&(frame as u32).to_le_bytes())?;
+        w.write_all(&(points.len() as u32).to_le_bytes())?;
+        for point in points {
+            w.write_all(&point.x.to_le_bytes())?;
+            w.write_all(&point.y.to_le_bytes())?;
         }
+        Ok(())
     }
+}
 
-    /// Creates a new file in the writers directory with each point written
-    /// on a separate line.
+// DataWriter ////////////////////////////////////////////////////////////////
+//
+// A utility object to simplify persistence of point data. DataWriter is
+// generic over any `W: io::Write` sink (a file, an in-memory buffer, a
+// socket, ...) and delegates the on-disk layout to a FrameSerializer so the
+// two concerns - where bytes go and what they look like - can vary
+// independently.
+
+pub struct DataWriter<W: Write, S: FrameSerializer> {
+    sink: W,
+    serializer: S,
+    counter: usize,
+}
+
+impl<W: Write, S: FrameSerializer> DataWriter<W, S> {
+    /// Wraps an existing `io::Write` sink, writing each frame through
+    /// `serializer`.
+    pub fn new(sink: W, serializer: S) -> DataWriter<W, S> {
+        DataWriter { sink, serializer, counter: 0 }
+    }
+
+    /// Serializes one frame of points into the sink and advances the frame
+    /// counter.
     pub fn write(&mut self, points: Vec<Point>) {
-        let path = format!("{}/frame-{}.txt", self.directory, self.counter);
-        if let Err(e) = self.write_points(points, path) {
+        if let Err(e) = self.serializer.write_frame(&mut self.sink, self.counter, &points) {
             panic!("Error writing data. {}", e)
         }
         self.counter += 1;
     }
+}
 
-    fn write_points(&self, points: Vec<Point>, path: String) -> std::io::Result<()> {
-        let mut file = fs::File::create(path)?;
-        for point in points { writeln!(file, "{},{}", point.x, point.y)?; }
-        Ok(())
+impl DataWriter<fs::File, CsvSerializer> {
+    /// Creates a new directory (if needed) and returns a CSV-serializing
+    /// writer that streams every frame into a single `data.csv` file inside
+    /// it.
+    pub fn in_directory(directory: &str) -> DataWriter<fs::File, CsvSerializer> {
+        if !Path::new(directory).exists() {
+            fs::create_dir(directory)
+                .expect("Couldn't create dir.");
+        }
+        let path = format!("{}/data.csv", directory);
+        let file = fs::File::create(path)
+            .expect("Couldn't create file.");
+        DataWriter::new(file, CsvSerializer)
+    }
+}
+
+// OutputSink ////////////////////////////////////////////////////////////////
+//
+// An OutputSink is what Environment hands each frame's points to. SyncSink
+// writes them on the calling thread, like DataWriter always has; AsyncSink
+// ships them to a background thread so the integrator never blocks on I/O.
+// `flush` lets a caller drain whatever is still in flight before a run ends.
+
+pub trait OutputSink {
+    fn send(&mut self, points: Vec<Point>);
+    fn flush(&mut self);
+}
+
+// SyncSink //////////////////////////////////////////////////////////////////
+
+pub struct SyncSink<W: Write, S: FrameSerializer> {
+    writer: DataWriter<W, S>,
+}
+
+impl<W: Write, S: FrameSerializer> SyncSink<W, S> {
+    pub fn new(writer: DataWriter<W, S>) -> SyncSink<W, S> {
+        SyncSink { writer }
+    }
+}
+
+impl<W: Write, S: FrameSerializer> OutputSink for SyncSink<W, S> {
+    fn send(&mut self, points: Vec<Point>) {
+        self.writer.write(points);
+    }
+
+    fn flush(&mut self) {}
+}
+
+// AsyncSink /////////////////////////////////////////////////////////////////
+//
+// Hands each frame to a bounded mpsc channel and lets a background thread
+// serialize it, so a slow sink (disk, socket) can lag behind the simulation
+// by up to `bound` frames instead of stalling it.
+
+pub struct AsyncSink {
+    sender: Option<mpsc::SyncSender<Vec<Point>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AsyncSink {
+    pub fn new<W, S>(mut writer: DataWriter<W, S>, bound: usize) -> AsyncSink
+        where W: Write + Send + 'static, S: FrameSerializer + Send + 'static
+    {
+        let (sender, receiver) = mpsc::sync_channel(bound);
+        let handle = thread::spawn(move || {
+            for points in receiver {
+                writer.write(points);
+            }
+        });
+        AsyncSink { sender: Some(sender), handle: Some(handle) }
+    }
+}
+
+impl OutputSink for AsyncSink {
+    fn send(&mut self, points: Vec<Point>) {
+        self.sender.as_ref()
+            .expect("Sent a frame to an AsyncSink after it was flushed.")
+            .send(points)
+            .expect("Background writer thread panicked.");
+    }
+
+    /// Closes the channel and blocks until the background thread has
+    /// serialized every frame still in flight.
+    fn flush(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("Background writer thread panicked.");
+        }
+    }
+}
+
+impl Drop for AsyncSink {
+    /// Mirrors `flush`, so dropping an AsyncSink without an explicit flush
+    /// can't silently truncate frames still buffered in the channel.
+    fn drop(&mut self) {
+        self.flush();
     }
 }
 
@@ -50,29 +196,101 @@ impl DataWriter {
 #[cfg(test)]
 mod tests {
     use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
     use super::*;
 
+    // A Write sink that can be read back after being moved onto another
+    // thread, used to observe what AsyncSink's background thread wrote.
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
-    fn data_writer_writes() {
+    fn in_directory_creates_the_directory_and_file() {
         // given
-        let mut writer = DataWriter::new("temp");
+        let mut writer = DataWriter::in_directory("temp_in_directory");
 
         // when
         writer.write(vec![Point::new(3.4, 6.7)]);
-        writer.write(vec![Point::new(6.4, 6.785)]);
 
         // then
-        let mut file = fs::File::open("temp/frame-0.txt").expect("Error opening file.");
-        let mut contents = String::new();
-        let _ = file.read_to_string(&mut contents);
-        assert_eq!(contents, "3.4,6.7\n".to_owned());
-
-        let mut file = fs::File::open("temp/frame-1.txt").expect("Error opening file.");
+        let mut file = fs::File::open("temp_in_directory/data.csv").expect("Error opening file.");
         let mut contents = String::new();
         let _ = file.read_to_string(&mut contents);
-        assert_eq!(contents, "6.4,6.785\n".to_owned());
+        assert_eq!(contents, "#,0\n3.4,6.7\n".to_owned());
 
         // after
-        fs::remove_dir_all("temp").expect("Error cleaning up test.");
+        fs::remove_dir_all("temp_in_directory").expect("Error cleaning up test.");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn data_writer_writes_csv_to_a_vec() {
+        // given
+        let mut writer = DataWriter::new(Vec::new(), CsvSerializer);
+
+        // when
+        writer.write(vec![Point::new(3.4, 6.7)]);
+        writer.write(vec![Point::new(6.4, 6.785)]);
+
+        // then
+        let contents = String::from_utf8(writer.sink).expect("Not valid UTF-8.");
+        assert_eq!(contents, "#,0\n3.4,6.7\n#,1\n6.4,6.785\n".to_owned());
+    }
+
+    #[test]
+    fn data_writer_writes_binary_frames() {
+        // given
+        let mut writer = DataWriter::new(Vec::new(), BinarySerializer);
+
+        // when
+        writer.write(vec![Point::new(1.0, 2.0)]);
+
+        // then
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&0u32.to_le_bytes());
+        expected.extend_from_slice(&1u32.to_le_bytes());
+        expected.extend_from_slice(&1.0f32.to_le_bytes());
+        expected.extend_from_slice(&2.0f32.to_le_bytes());
+        assert_eq!(writer.sink, expected);
+    }
+
+    #[test]
+    fn sync_sink_writes_on_the_calling_thread() {
+        // given
+        let mut sink = SyncSink::new(DataWriter::new(Vec::new(), CsvSerializer));
+
+        // when
+        sink.send(vec![Point::new(1.0, 2.0)]);
+        sink.flush();
+
+        // then
+        assert_eq!(sink.writer.sink, b"#,0\n1,2\n".to_vec());
+    }
+
+    #[test]
+    fn async_sink_flush_drains_the_background_thread() {
+        // given
+        let buf = SharedBuf::default();
+        let writer = DataWriter::new(buf.clone(), CsvSerializer);
+        let mut sink = AsyncSink::new(writer, 4);
+
+        // when
+        sink.send(vec![Point::new(1.0, 2.0)]);
+        sink.send(vec![Point::new(3.0, 4.0)]);
+        sink.flush();
+
+        // then
+        let contents = String::from_utf8(buf.0.lock().unwrap().clone()).expect("Not valid UTF-8.");
+        assert_eq!(contents, "#,0\n1,2\n#,1\n3,4\n".to_owned());
+    }
+}